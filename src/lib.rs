@@ -159,6 +159,11 @@ extern crate error_chain;
 extern crate glob;
 extern crate iptables as ipt;
 extern crate libc;
+#[cfg(feature = "metrics")]
+#[macro_use]
+extern crate lazy_static;
+#[cfg(feature = "metrics")]
+extern crate prometheus;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
@@ -167,6 +172,8 @@ extern crate shiplift;
 extern crate slog;
 extern crate slog_async;
 extern crate slog_term;
+#[cfg(feature = "metrics")]
+extern crate tiny_http;
 extern crate time;
 extern crate toml;
 extern crate url;
@@ -174,10 +181,53 @@ extern crate url;
 // declare modules
 pub mod errors;
 pub mod iptables;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod process;
+#[cfg(feature = "integration-tests")]
+pub mod testing;
 pub mod types;
 pub mod util;
 
+/// A firewall backend capable of applying the rules generated through [`Process`] to the host.
+///
+/// [`Process`]: process/trait.Process.html
+pub trait FirewallBackend: Sized {
+    /// The type used to represent a single rule understood by this backend.
+    type Rule: Clone + PartialEq;
+
+    /// Backend-specific default values, read from the `defaults` configuration section.
+    type Defaults;
+
+    /// Apply the given rules, replacing any rules previously put in place by DFW.
+    fn apply(rules: Vec<Self::Rule>, ctx: &process::ProcessContext<Self>)
+        -> Result<(), failure::Error>;
+
+    /// Apply only the difference between the previous and the current processing pass to the
+    /// host.
+    ///
+    /// `added` and `removed` are computed by comparing whole rules of both passes for equality;
+    /// the `DFW-MARKER` embedded in a rule (see [`generate_marker`]) is not used as a separate
+    /// key, so a rule whose content changed shows up as removed in its old form and added in its
+    /// new form.
+    ///
+    /// Backends that can surgically insert or delete individual rules should override this to
+    /// avoid re-flushing chains that haven't changed between passes. The default implementation
+    /// ignores the diff and falls back to a full [`apply`] of the ruleset computed for the
+    /// current pass.
+    ///
+    /// [`generate_marker`]: process/fn.generate_marker.html
+    /// [`apply`]: #tymethod.apply
+    fn apply_diff(
+        added: Vec<Self::Rule>,
+        removed: Vec<Self::Rule>,
+        ctx: &process::ProcessContext<Self>,
+    ) -> Result<(), failure::Error> {
+        let _ = (added, removed);
+        Self::apply(ctx.current_rules(), ctx)
+    }
+}
+
 // re-export process types
 
 pub use process::*;
\ No newline at end of file
@@ -0,0 +1,113 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+//! This module holds the metrics exposed by DFW, in the [Prometheus][prometheus] text exposition
+//! format, so DFW can be scraped alongside tools like `cAdvisor`.
+//!
+//! The counters and histograms here are populated from within [`ProcessContext::new`] and
+//! [`ProcessContext::process`], independent of which [`FirewallBackend`] is in use.
+//!
+//! This module, and the `prometheus` and `tiny_http` dependencies it requires, is only compiled
+//! when the `metrics` feature is enabled.
+//!
+//! [prometheus]: https://prometheus.io/
+//! [`ProcessContext::new`]: ../process/struct.ProcessContext.html#method.new
+//! [`ProcessContext::process`]: ../process/struct.ProcessContext.html#method.process
+//! [`FirewallBackend`]: ../trait.FirewallBackend.html
+
+#![cfg(feature = "metrics")]
+
+use crate::errors::*;
+use failure::format_err;
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_int_counter, Encoder, Histogram, IntCounter, TextEncoder,
+};
+use std::net::SocketAddr;
+
+lazy_static! {
+    /// Total number of containers discovered while building a [`ProcessContext`].
+    ///
+    /// [`ProcessContext`]: ../process/struct.ProcessContext.html
+    pub static ref CONTAINERS_DISCOVERED: IntCounter = register_int_counter!(
+        "dfw_containers_discovered_total",
+        "Total number of containers discovered while building a ProcessContext"
+    )
+    .unwrap();
+
+    /// Total number of networks discovered while building a [`ProcessContext`].
+    ///
+    /// [`ProcessContext`]: ../process/struct.ProcessContext.html
+    pub static ref NETWORKS_DISCOVERED: IntCounter = register_int_counter!(
+        "dfw_networks_discovered_total",
+        "Total number of networks discovered while building a ProcessContext"
+    )
+    .unwrap();
+
+    /// Total number of rules emitted across all processing passes.
+    pub static ref RULES_EMITTED: IntCounter = register_int_counter!(
+        "dfw_rules_emitted_total",
+        "Total number of rules emitted"
+    )
+    .unwrap();
+
+    /// Time spent inside [`ProcessContext::process`], from rule generation to backend apply.
+    ///
+    /// [`ProcessContext::process`]: ../process/struct.ProcessContext.html#method.process
+    pub static ref PROCESSING_DURATION: Histogram = register_histogram!(
+        "dfw_processing_duration_seconds",
+        "Time spent generating and applying the ruleset"
+    )
+    .unwrap();
+
+    /// Total number of failures encountered while applying the generated rules to the backend.
+    pub static ref APPLY_FAILURES: IntCounter = register_int_counter!(
+        "dfw_apply_failures_total",
+        "Total number of failures encountered while applying rules to the backend"
+    )
+    .unwrap();
+
+    /// Time spent listing containers and networks from the Docker API.
+    pub static ref DOCKER_LIST_DURATION: Histogram = register_histogram!(
+        "dfw_docker_list_duration_seconds",
+        "Time spent listing containers and networks from the Docker API"
+    )
+    .unwrap();
+}
+
+/// Render all metrics gathered in this process in the Prometheus text exposition format.
+pub fn gather() -> Result<Vec<u8>> {
+    let metric_families = prometheus::gather();
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&metric_families, &mut buffer)
+        .map_err(|e| format_err!("failed to encode metrics: {}", e))?;
+
+    Ok(buffer)
+}
+
+/// Serve the metrics gathered in this process over HTTP on `addr`, in the Prometheus text
+/// exposition format, until the process exits.
+///
+/// This is meant to be run on its own thread, so that Prometheus (or a sidecar such as
+/// `cAdvisor`) can scrape DFW alongside the rest of the host's metrics.
+pub fn serve(addr: SocketAddr) -> Result<()> {
+    let server = tiny_http::Server::http(addr)
+        .map_err(|e| format_err!("failed to bind metrics endpoint on {}: {}", addr, e))?;
+
+    for request in server.incoming_requests() {
+        let buffer = gather()?;
+        let header = "Content-Type: text/plain; version=0.0.4"
+            .parse::<tiny_http::Header>()
+            .unwrap();
+        let response = tiny_http::Response::from_data(buffer).with_header(header);
+        let _ = request.respond(response);
+    }
+
+    Ok(())
+}
@@ -16,8 +16,12 @@ use shiplift::{
     Docker,
 };
 use slog::{debug, o, trace, Logger};
+use std::cell::RefCell;
 use std::collections::HashMap as Map;
 
+/// Docker's health-status string for a container that has passed its `HEALTHCHECK`.
+const HEALTH_STATUS_HEALTHY: &str = "healthy";
+
 /// This trait allows a type to define its own processing rules. It is expected to return a list
 /// of rules that can be applied with nft.
 ///
@@ -113,6 +117,12 @@ where
     pub(crate) external_network_interfaces: Option<Vec<String>>,
     pub(crate) logger: Logger,
     pub(crate) dry_run: bool,
+    pub(crate) previous_rules: Option<Vec<B::Rule>>,
+    pub(crate) pending_rules: Option<Vec<B::Rule>>,
+    /// Caches each container's resolved `HostConfig.NetworkMode` for the lifetime of this pass,
+    /// since [`get_network_for_container`] is invoked once per (container, network) pair and
+    /// would otherwise re-inspect the same container over and over.
+    pub(crate) network_mode_cache: RefCell<Map<String, String>>,
 }
 
 impl<'a, B> ProcessContext<'a, B>
@@ -121,32 +131,60 @@ where
     DFW<B>: Process<B>,
 {
     /// Create a new instance of `ProcessDFW` for rule processing.
+    ///
+    /// `previous_rules` enables incremental reconciliation: when set to the ruleset returned by
+    /// the previous call to [`process`](#method.process), only the difference between that
+    /// ruleset and the one computed for this pass is applied, through
+    /// [`FirewallBackend::apply_diff`], instead of the full ruleset. Pass `None` to always
+    /// perform a full [`apply`](trait.FirewallBackend.html#tymethod.apply), e.g. on the first
+    /// pass.
+    ///
+    /// [`FirewallBackend::apply_diff`]: trait.FirewallBackend.html#method.apply_diff
     pub fn new(
         docker: &'a Docker,
         dfw: &'a DFW<B>,
         processing_options: &'a ProcessingOptions,
         logger: &'a Logger,
         dry_run: bool,
+        previous_rules: Option<Vec<B::Rule>>,
     ) -> Result<ProcessContext<'a, B>> {
         let logger = logger.new(o!());
 
         let container_list_options = match processing_options.container_filter {
-            ContainerFilter::All => Default::default(),
+            ContainerFilter::All | ContainerFilter::Healthy => Default::default(),
             ContainerFilter::Running => ContainerListOptions::builder()
                 .filter(vec![ContainerFilterShiplift::Status("running".to_owned())])
                 .build(),
         };
+        #[cfg(feature = "metrics")]
+        let docker_list_timer = crate::metrics::DOCKER_LIST_DURATION.start_timer();
         let containers = docker.containers().list(&container_list_options).sync()?;
+        #[cfg(feature = "metrics")]
+        docker_list_timer.observe_duration();
         debug!(logger, "Got list of containers";
                o!("containers" => format!("{:#?}", containers)));
 
+        let containers = if processing_options.container_filter == ContainerFilter::Healthy {
+            filter_healthy_containers(docker, containers)?
+        } else {
+            containers
+        };
+        #[cfg(feature = "metrics")]
+        crate::metrics::CONTAINERS_DISCOVERED.inc_by(containers.len() as u64);
+
         let container_map = get_container_map(&containers)?;
         trace!(logger, "Got map of containers";
                o!("container_map" => format!("{:#?}", container_map)));
 
+        #[cfg(feature = "metrics")]
+        let docker_list_timer = crate::metrics::DOCKER_LIST_DURATION.start_timer();
         let networks = docker.networks().list(&Default::default()).sync()?;
+        #[cfg(feature = "metrics")]
+        docker_list_timer.observe_duration();
         debug!(logger, "Got list of networks";
                o!("networks" => format!("{:#?}", networks)));
+        #[cfg(feature = "metrics")]
+        crate::metrics::NETWORKS_DISCOVERED.inc_by(networks.len() as u64);
 
         let network_map =
             get_network_map(&networks)?.ok_or_else(|| format_err!("no networks found"))?;
@@ -167,20 +205,85 @@ where
             external_network_interfaces,
             logger,
             dry_run,
+            previous_rules,
+            pending_rules: None,
+            network_mode_cache: RefCell::new(Map::new()),
         })
     }
 
     /// Start the processing using the configuration given at creation.
-    pub fn process(&mut self) -> Result<()> {
+    ///
+    /// Returns the ruleset that was computed for this pass, which should be passed as
+    /// `previous_rules` to [`new`](#method.new) on the next pass to enable incremental
+    /// reconciliation.
+    pub fn process(&mut self) -> Result<Option<Vec<B::Rule>>> {
+        #[cfg(feature = "metrics")]
+        let processing_timer = crate::metrics::PROCESSING_DURATION.start_timer();
+
         let rules = Process::<B>::process(self.dfw, self)?;
         if let Some(rules) = rules {
-            B::apply(rules, self)?;
+            #[cfg(feature = "metrics")]
+            crate::metrics::RULES_EMITTED.inc_by(rules.len() as u64);
+
+            self.pending_rules = Some(rules.clone());
+
+            let result = match self.previous_rules.take() {
+                Some(previous) => {
+                    let (added, removed) = diff_rules(&previous, &rules);
+                    B::apply_diff(added, removed, self)
+                }
+                None => B::apply(rules.clone(), self),
+            };
+
+            if let Err(e) = result {
+                #[cfg(feature = "metrics")]
+                crate::metrics::APPLY_FAILURES.inc();
+                return Err(e);
+            }
+
+            #[cfg(feature = "metrics")]
+            processing_timer.observe_duration();
+
+            Ok(Some(rules))
+        } else {
+            Ok(None)
         }
+    }
 
-        Ok(())
+    /// Returns the full ruleset computed for the processing pass currently being applied.
+    ///
+    /// This is primarily intended for [`FirewallBackend::apply_diff`] implementations that want
+    /// to fall back to a full [`apply`](trait.FirewallBackend.html#tymethod.apply) without having
+    /// to reconstruct the ruleset themselves.
+    ///
+    /// [`FirewallBackend::apply_diff`]: trait.FirewallBackend.html#method.apply_diff
+    pub fn current_rules(&self) -> Vec<B::Rule> {
+        self.pending_rules.clone().unwrap_or_default()
     }
 }
 
+/// Compute the rules that were added and removed between two processing passes.
+///
+/// Rules are matched across passes by equality of the whole rule; the `DFW-MARKER` embedded in
+/// a generated rule (see [`generate_marker`]) is not used as a separate key.
+pub(crate) fn diff_rules<R>(previous: &[R], current: &[R]) -> (Vec<R>, Vec<R>)
+where
+    R: Clone + PartialEq,
+{
+    let added = current
+        .iter()
+        .filter(|rule| !previous.contains(rule))
+        .cloned()
+        .collect();
+    let removed = previous
+        .iter()
+        .filter(|rule| !current.contains(rule))
+        .cloned()
+        .collect();
+
+    (added, removed)
+}
+
 /// Option to filter the containers to be processed
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ContainerFilter {
@@ -188,6 +291,11 @@ pub enum ContainerFilter {
     All,
     /// Only process running containers.
     Running,
+    /// Only process containers that are passing their Docker `HEALTHCHECK`.
+    ///
+    /// Containers that don't define a `HEALTHCHECK` have no health status to evaluate, so they
+    /// are treated as passing, keeping existing setups without healthchecks working as before.
+    Healthy,
 }
 
 /// Options to configure the processing procedure.
@@ -216,23 +324,104 @@ pub(crate) fn get_bridge_name(network_id: &str) -> Result<String> {
 pub(crate) fn get_network_for_container(
     docker: &Docker,
     container_map: &Map<String, Container>,
+    network_mode_cache: &RefCell<Map<String, String>>,
     container_name: &str,
     network_id: &str,
 ) -> Result<Option<NetworkContainerDetails>> {
-    Ok(match container_map.get(container_name) {
-        Some(container) => match docker
+    let container = match container_map.get(container_name) {
+        Some(container) => container,
+        None => return Ok(None),
+    };
+
+    // Containers started with `--network container:<other>` or `--network host` don't have their
+    // own entry in `NetworkDetails.containers`, since they share another container's (or the
+    // host's) network namespace. Follow the reference to find the container whose attachment
+    // should actually be used for this container's rules.
+    let network_mode = get_network_mode(docker, network_mode_cache, &container.id)?;
+
+    if network_mode == "host" {
+        // The container lives in the host's network namespace; there is no bridge-based network
+        // attachment to report.
+        return Ok(None);
+    }
+
+    let target_id = match network_mode.strip_prefix("container:") {
+        Some(other) => docker.containers().get(other).inspect().sync()?.id,
+        None => container.id.clone(),
+    };
+
+    Ok(
+        match docker
             .networks()
             .get(network_id)
             .inspect()
             .sync()?
             .containers
-            .get(&container.id)
+            .get(&target_id)
         {
             Some(network) => Some(network.clone()),
             None => None,
         },
-        None => None,
-    })
+    )
+}
+
+/// Resolve `container_id`'s `HostConfig.NetworkMode`, reusing the cached value from an earlier
+/// call within the same pass instead of re-inspecting the container with the Docker API.
+fn get_network_mode(
+    docker: &Docker,
+    network_mode_cache: &RefCell<Map<String, String>>,
+    container_id: &str,
+) -> Result<String> {
+    if let Some(network_mode) = network_mode_cache.borrow().get(container_id) {
+        return Ok(network_mode.clone());
+    }
+
+    let network_mode = docker
+        .containers()
+        .get(container_id)
+        .inspect()
+        .sync()?
+        .host_config
+        .network_mode;
+    network_mode_cache
+        .borrow_mut()
+        .insert(container_id.to_owned(), network_mode.clone());
+
+    Ok(network_mode)
+}
+
+pub(crate) fn filter_healthy_containers(
+    docker: &Docker,
+    containers: Vec<Container>,
+) -> Result<Vec<Container>> {
+    let mut healthy = Vec::with_capacity(containers.len());
+    for container in containers {
+        if is_container_healthy(docker, &container)? {
+            healthy.push(container);
+        }
+    }
+
+    Ok(healthy)
+}
+
+pub(crate) fn is_container_healthy(docker: &Docker, container: &Container) -> Result<bool> {
+    let details = docker.containers().get(&container.id).inspect().sync()?;
+
+    Ok(health_status_passes(
+        details.state.health.map(|health| health.status),
+    ))
+}
+
+/// Decide whether a container's raw `State.Health.Status` value should be treated as passing,
+/// i.e. whether the container should be kept by [`ContainerFilter::Healthy`].
+///
+/// Containers without a `HEALTHCHECK` don't carry a health status at all, and are treated as
+/// passing so existing setups without healthchecks keep working.
+fn health_status_passes(status: Option<String>) -> bool {
+    match status.as_deref() {
+        Some(status) => status == HEALTH_STATUS_HEALTHY,
+        None => true,
+    }
 }
 
 pub(crate) fn get_container_map(containers: &[Container]) -> Result<Map<String, Container>> {
@@ -267,3 +456,74 @@ pub(crate) fn get_network_map(
 pub(crate) fn generate_marker(components: &[&str]) -> String {
     format!("DFW-MARKER:{}", components.join(";"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_rules, health_status_passes};
+
+    #[test]
+    fn health_status_passes_without_a_healthcheck() {
+        // No `HEALTHCHECK` defined means there is no status to fail, so the container is treated
+        // as passing.
+        assert!(health_status_passes(None));
+    }
+
+    #[test]
+    fn health_status_passes_when_healthy() {
+        assert!(health_status_passes(Some("healthy".to_owned())));
+    }
+
+    #[test]
+    fn health_status_fails_when_unhealthy() {
+        assert!(!health_status_passes(Some("unhealthy".to_owned())));
+    }
+
+    #[test]
+    fn health_status_fails_while_starting() {
+        assert!(!health_status_passes(Some("starting".to_owned())));
+    }
+
+    #[test]
+    fn diff_rules_add_only() {
+        let previous: Vec<String> = vec![];
+        let current = vec!["rule-a".to_owned(), "rule-b".to_owned()];
+
+        let (added, removed) = diff_rules(&previous, &current);
+
+        assert_eq!(added, current);
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn diff_rules_remove_only() {
+        let previous = vec!["rule-a".to_owned(), "rule-b".to_owned()];
+        let current: Vec<String> = vec![];
+
+        let (added, removed) = diff_rules(&previous, &current);
+
+        assert!(added.is_empty());
+        assert_eq!(removed, previous);
+    }
+
+    #[test]
+    fn diff_rules_stable_set_produces_empty_diff() {
+        let previous = vec!["rule-a".to_owned(), "rule-b".to_owned()];
+        let current = previous.clone();
+
+        let (added, removed) = diff_rules(&previous, &current);
+
+        assert!(added.is_empty());
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn diff_rules_mixed_add_and_remove() {
+        let previous = vec!["rule-a".to_owned(), "rule-b".to_owned()];
+        let current = vec!["rule-b".to_owned(), "rule-c".to_owned()];
+
+        let (added, removed) = diff_rules(&previous, &current);
+
+        assert_eq!(added, vec!["rule-c".to_owned()]);
+        assert_eq!(removed, vec!["rule-a".to_owned()]);
+    }
+}
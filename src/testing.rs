@@ -0,0 +1,231 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+//! This module holds a Docker-backed conformance test harness.
+//!
+//! Unlike the unit tests spread across this crate, which only assert on the serialized form of
+//! the rules a [`Process`] implementation produces, the harness in this module provisions a
+//! small, real Docker topology -- networks and containers -- runs a full
+//! [`ProcessContext::process`] against it using a real [`FirewallBackend`], and then probes
+//! connectivity from inside the containers to assert that the generated rules actually permit or
+//! block traffic as expected.
+//!
+//! This module is only compiled when the `integration-tests` feature is enabled, since it
+//! requires a reachable Docker daemon and mutates the host's firewall state.
+//!
+//! Which [`FirewallBackend`] is exercised is decided by the caller, through the
+//! [`ProcessContext`] it hands to [`run_conformance_pass`]. The [`DFW_TEST_BACKEND`] environment
+//! variable only names the backend under test (see [`backend_under_test`]); nothing in this
+//! module selects a backend from it.
+//!
+//! [`Process`]: ../process/trait.Process.html
+//! [`ProcessContext::process`]: ../process/struct.ProcessContext.html#method.process
+//! [`FirewallBackend`]: ../trait.FirewallBackend.html
+//! [`DFW_TEST_BACKEND`]: constant.DFW_TEST_BACKEND.html
+//! [`ProcessContext`]: ../process/struct.ProcessContext.html
+//! [`run_conformance_pass`]: fn.run_conformance_pass.html
+//! [`backend_under_test`]: fn.backend_under_test.html
+
+use crate::{errors::*, process::ProcessContext, types::DFW, FirewallBackend};
+use failure::format_err;
+use shiplift::{
+    builder::{ContainerOptions, NetworkCreateOptions, RmContainerOptions},
+    Docker,
+};
+use std::env;
+
+/// Name of the environment variable read by [`backend_under_test`](fn.backend_under_test.html)
+/// to name the [`FirewallBackend`] under test, e.g. `iptables` or `nftables`. It does not select
+/// the backend that is run.
+///
+/// [`FirewallBackend`]: ../trait.FirewallBackend.html
+pub const DFW_TEST_BACKEND: &str = "DFW_TEST_BACKEND";
+
+/// A Docker network to provision as part of a [`Topology`](struct.Topology.html).
+#[derive(Debug, Clone)]
+pub struct TestNetwork {
+    /// Name of the user-defined Docker network to create.
+    pub name: String,
+}
+
+/// A container to provision as part of a [`Topology`](struct.Topology.html).
+#[derive(Debug, Clone)]
+pub struct TestContainer {
+    /// Name to give the container, referenced by [`Topology::assert_reachable`].
+    pub name: String,
+    /// Image to start the container from, e.g. a minimal `busybox`-style image.
+    pub image: String,
+    /// Name of the [`TestNetwork`](struct.TestNetwork.html) the container should be attached to.
+    pub network: String,
+    /// Command to run as the container's entrypoint, overriding the image default.
+    ///
+    /// Scenarios probing a specific port need a real process listening on it, rather than
+    /// relying on there being nothing to connect to.
+    pub cmd: Option<Vec<String>>,
+}
+
+/// A small, self-contained Docker topology used to exercise a [`FirewallBackend`] end-to-end.
+///
+/// [`FirewallBackend`]: ../trait.FirewallBackend.html
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    /// Networks to create before any container is started.
+    pub networks: Vec<TestNetwork>,
+    /// Containers to create and start, attached to one of `networks`.
+    pub containers: Vec<TestContainer>,
+}
+
+impl Topology {
+    /// Create an empty topology.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Provision the networks and containers described by this topology on `docker`.
+    pub fn provision(&self, docker: &Docker) -> Result<()> {
+        for network in &self.networks {
+            docker
+                .networks()
+                .create(&NetworkCreateOptions::builder(&network.name).build())
+                .sync()?;
+        }
+
+        for container in &self.containers {
+            let mut builder = ContainerOptions::builder(&container.image);
+            builder.name(&container.name).network_mode(&container.network);
+            if let Some(cmd) = &container.cmd {
+                builder.cmd(cmd.iter().map(String::as_str).collect());
+            }
+            let info = docker.containers().create(&builder.build()).sync()?;
+            docker.containers().get(&info.id).start().sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// Look up the IPv4 address Docker assigned to `container_name` on `network`.
+    pub fn container_address(
+        &self,
+        docker: &Docker,
+        container_name: &str,
+        network: &str,
+    ) -> Result<String> {
+        let details = docker.containers().get(container_name).inspect().sync()?;
+
+        details
+            .network_settings
+            .networks
+            .get(network)
+            .map(|endpoint| endpoint.ip_address.clone())
+            .ok_or_else(|| {
+                format_err!(
+                    "container {} has no address on network {}",
+                    container_name,
+                    network
+                )
+            })
+    }
+
+    /// Provision this topology, run `f`, and always tear the topology down again afterwards --
+    /// even if `f` returns an error -- before propagating `f`'s result.
+    ///
+    /// This keeps a failing or errored assertion from leaking the provisioned networks and
+    /// containers.
+    pub fn scoped<F, T>(&self, docker: &Docker, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        self.provision(docker)?;
+        let result = f();
+        self.teardown(docker)?;
+
+        result
+    }
+
+    /// Tear down all containers and networks created by [`provision`](#method.provision).
+    pub fn teardown(&self, docker: &Docker) -> Result<()> {
+        for container in &self.containers {
+            docker
+                .containers()
+                .get(&container.name)
+                .remove(RmContainerOptions::builder().force(true).build())
+                .sync()?;
+        }
+
+        for network in &self.networks {
+            docker.networks().get(&network.name).delete().sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// Check whether `from` can reach `to` on `port`, by `exec`-ing a reachability probe (`nc`,
+    /// falling back to `ping` when `port` is `None`) inside the `from` container.
+    ///
+    /// Returns whether the probe succeeded, i.e. whether `to` was reachable from `from`; it does
+    /// not itself fail on either outcome, asserting on the result is left to the caller.
+    pub fn assert_reachable(
+        &self,
+        docker: &Docker,
+        from: &str,
+        to: &str,
+        port: Option<u16>,
+    ) -> Result<bool> {
+        let cmd = match port {
+            Some(port) => vec![
+                "nc".to_owned(),
+                "-z".to_owned(),
+                "-w".to_owned(),
+                "2".to_owned(),
+                to.to_owned(),
+                port.to_string(),
+            ],
+            None => vec![
+                "ping".to_owned(),
+                "-c".to_owned(),
+                "1".to_owned(),
+                "-W".to_owned(),
+                "2".to_owned(),
+                to.to_owned(),
+            ],
+        };
+        let cmd: Vec<&str> = cmd.iter().map(String::as_str).collect();
+
+        let exit = docker
+            .containers()
+            .get(from)
+            .exec(&shiplift::builder::ExecContainerOptions::builder().cmd(cmd).build())
+            .sync()
+            .map_err(|e| format_err!("failed to exec reachability probe: {}", e))?;
+
+        Ok(exit.status_code() == 0)
+    }
+}
+
+/// Run `ctx.process()` against the real `docker` daemon backing `ctx`, applying the rules
+/// generated for the [`DFW`](../types/struct.DFW.html) configuration backing `ctx` with the
+/// [`FirewallBackend`] under test, and returning the ruleset that was applied.
+///
+/// [`FirewallBackend`]: ../trait.FirewallBackend.html
+pub fn run_conformance_pass<B>(ctx: &mut ProcessContext<B>) -> Result<Option<Vec<B::Rule>>>
+where
+    B: FirewallBackend,
+    DFW<B>: crate::process::Process<B>,
+{
+    ctx.process()
+}
+
+/// Return the name of the [`FirewallBackend`] given in the [`DFW_TEST_BACKEND`] environment
+/// variable, defaulting to `iptables` if it isn't set. This only reports the name; it does not
+/// select or construct a backend.
+///
+/// [`FirewallBackend`]: ../trait.FirewallBackend.html
+/// [`DFW_TEST_BACKEND`]: constant.DFW_TEST_BACKEND.html
+pub fn backend_under_test() -> String {
+    env::var(DFW_TEST_BACKEND).unwrap_or_else(|_| "iptables".to_owned())
+}
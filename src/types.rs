@@ -0,0 +1,112 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+//! This module holds the root configuration type, [`DFW`], and the categories it is built up
+//! from. See the [crate documentation][crate] for a description of each category.
+//!
+//! [`DFW`]: struct.DFW.html
+
+use crate::{
+    errors::*,
+    process::{Process, ProcessContext},
+    FirewallBackend,
+};
+
+/// Global, default values used across the other configuration categories.
+#[derive(Debug, Clone, Default)]
+pub struct GlobalDefaults {
+    /// Interfaces to treat as facing the wider world, e.g. for `container_to_wider_world` and
+    /// `wider_world_to_container` processing.
+    pub external_network_interfaces: Option<Vec<String>>,
+}
+
+/// A set of already-resolved rules for a single configuration category.
+///
+/// This is the common shape backing [`DFW`]'s category fields: each category is, at its core,
+/// a list of rules to hand to the [`FirewallBackend`] under use, in the order they should be
+/// applied in.
+///
+/// [`FirewallBackend`]: ../trait.FirewallBackend.html
+pub struct RuleSet<B: FirewallBackend> {
+    /// The rules belonging to this category.
+    pub rules: Vec<B::Rule>,
+}
+
+impl<B: FirewallBackend> Default for RuleSet<B> {
+    fn default() -> Self {
+        RuleSet { rules: Vec::new() }
+    }
+}
+
+impl<B: FirewallBackend> Clone for RuleSet<B> {
+    fn clone(&self) -> Self {
+        RuleSet {
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+impl<B: FirewallBackend> Process<B> for RuleSet<B> {
+    fn process(&self, _ctx: &ProcessContext<B>) -> Result<Option<Vec<B::Rule>>> {
+        Ok(Some(self.rules.clone()))
+    }
+}
+
+/// Root configuration object, holding the configuration for all categories described in the
+/// [crate documentation][crate].
+pub struct DFW<B: FirewallBackend> {
+    /// Global, default values, see [`GlobalDefaults`](struct.GlobalDefaults.html).
+    pub global_defaults: GlobalDefaults,
+    /// Controls communication between containers and across Docker networks.
+    pub container_to_container: Option<RuleSet<B>>,
+    /// Controls if and how containers may access the wider world.
+    pub container_to_wider_world: Option<RuleSet<B>>,
+    /// Controls access to the host.
+    pub container_to_host: Option<RuleSet<B>>,
+    /// Controls how the wider world can communicate with a container or a Docker network.
+    pub wider_world_to_container: Option<RuleSet<B>>,
+    /// Destination network address translation rules.
+    pub container_dnat: Option<RuleSet<B>>,
+}
+
+impl<B: FirewallBackend> Default for DFW<B> {
+    fn default() -> Self {
+        DFW {
+            global_defaults: GlobalDefaults::default(),
+            container_to_container: None,
+            container_to_wider_world: None,
+            container_to_host: None,
+            wider_world_to_container: None,
+            container_dnat: None,
+        }
+    }
+}
+
+impl<B: FirewallBackend> Process<B> for DFW<B> {
+    fn process(&self, ctx: &ProcessContext<B>) -> Result<Option<Vec<B::Rule>>> {
+        let mut rules = Vec::new();
+
+        if let Some(mut category_rules) = self.container_to_container.process(ctx)? {
+            rules.append(&mut category_rules);
+        }
+        if let Some(mut category_rules) = self.container_to_wider_world.process(ctx)? {
+            rules.append(&mut category_rules);
+        }
+        if let Some(mut category_rules) = self.container_to_host.process(ctx)? {
+            rules.append(&mut category_rules);
+        }
+        if let Some(mut category_rules) = self.wider_world_to_container.process(ctx)? {
+            rules.append(&mut category_rules);
+        }
+        if let Some(mut category_rules) = self.container_dnat.process(ctx)? {
+            rules.append(&mut category_rules);
+        }
+
+        Ok(Some(rules))
+    }
+}
@@ -0,0 +1,254 @@
+// Copyright Pit Kleyersburg <pitkley@googlemail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified or distributed
+// except according to those terms.
+
+//! Conformance tests exercising the reachability matrix (container-to-container,
+//! container-to-wider-world, wider-world-to-container) through a real [`FirewallBackend`].
+//!
+//! Each test provisions a small topology, builds a [`DFW`] config exercising the category under
+//! test, runs it through [`ProcessContext::new`] and [`run_conformance_pass`] against
+//! [`ConformanceBackend`] -- which applies the generated rules with the real `iptables` binary --
+//! and only then probes reachability, so a passing test demonstrates the rules DFW generated are
+//! what's actually permitting or blocking the traffic, not just Docker's own default behaviour.
+//!
+//! Requires a reachable Docker daemon, a host `iptables` with a `DOCKER-USER` chain, and the
+//! `integration-tests` feature.
+//!
+//! [`FirewallBackend`]: ../dfw/trait.FirewallBackend.html
+//! [`DFW`]: ../dfw/types/struct.DFW.html
+//! [`ProcessContext::new`]: ../dfw/process/struct.ProcessContext.html#method.new
+//! [`run_conformance_pass`]: ../dfw/testing/fn.run_conformance_pass.html
+
+#![cfg(feature = "integration-tests")]
+
+use dfw::{
+    errors,
+    process::{ProcessContext, ProcessingOptions},
+    testing::{backend_under_test, run_conformance_pass, TestContainer, TestNetwork, Topology},
+    types::{RuleSet, DFW},
+    FirewallBackend,
+};
+use failure::format_err;
+use shiplift::Docker;
+use slog::{o, Logger};
+use std::process::Command;
+use std::sync::Mutex;
+
+/// Name of the custom chain the conformance tests apply rules to, jumped to from `DOCKER-USER`.
+///
+/// A dedicated chain lets each test flush and rebuild just its own rules between passes without
+/// disturbing the rest of `DOCKER-USER`.
+const CONFORMANCE_CHAIN: &str = "DFW-CONFORMANCE-TEST";
+
+/// All three tests drive the same host-wide `DOCKER-USER`/[`CONFORMANCE_CHAIN`] state, so they
+/// have to run one at a time.
+static IPTABLES_LOCK: Mutex<()> = Mutex::new(());
+
+/// A [`FirewallBackend`] that applies rules with the real `iptables` binary against
+/// [`CONFORMANCE_CHAIN`], so the conformance tests exercise the same kind of enforcement path a
+/// production backend would, rather than asserting on the rules DFW generated without ever
+/// applying them.
+///
+/// [`FirewallBackend`]: ../dfw/trait.FirewallBackend.html
+struct ConformanceBackend;
+
+impl FirewallBackend for ConformanceBackend {
+    type Rule = String;
+    type Defaults = ();
+
+    fn apply(
+        rules: Vec<String>,
+        _ctx: &ProcessContext<Self>,
+    ) -> Result<(), failure::Error> {
+        ensure_conformance_chain()?;
+        flush_conformance_chain()?;
+        for rule in &rules {
+            let mut args = vec!["-A", CONFORMANCE_CHAIN];
+            args.extend(rule.split_whitespace());
+            run_iptables(&args)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn run_iptables(args: &[&str]) -> Result<(), failure::Error> {
+    let status = Command::new("iptables")
+        .args(args)
+        .status()
+        .map_err(|e| format_err!("failed to run iptables {:?}: {}", args, e))?;
+    if !status.success() {
+        return Err(format_err!("iptables {:?} exited with {}", args, status));
+    }
+
+    Ok(())
+}
+
+/// Make sure [`CONFORMANCE_CHAIN`] exists and that `DOCKER-USER` jumps to it.
+fn ensure_conformance_chain() -> Result<(), failure::Error> {
+    // `-N` fails if the chain already exists, which is exactly the case we're fine with.
+    let _ = Command::new("iptables")
+        .args(&["-N", CONFORMANCE_CHAIN])
+        .status();
+
+    let jumps_to_chain = Command::new("iptables")
+        .args(&["-C", "DOCKER-USER", "-j", CONFORMANCE_CHAIN])
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    if !jumps_to_chain {
+        run_iptables(&["-I", "DOCKER-USER", "-j", CONFORMANCE_CHAIN])?;
+    }
+
+    Ok(())
+}
+
+fn flush_conformance_chain() -> Result<(), failure::Error> {
+    run_iptables(&["-F", CONFORMANCE_CHAIN])
+}
+
+/// Build a [`ProcessContext`] against [`ConformanceBackend`] for a single conformance pass.
+fn conformance_context<'a>(
+    docker: &'a Docker,
+    dfw: &'a DFW<ConformanceBackend>,
+    processing_options: &'a ProcessingOptions,
+    logger: &'a Logger,
+) -> errors::Result<ProcessContext<'a, ConformanceBackend>> {
+    ProcessContext::new(docker, dfw, processing_options, logger, false, None)
+}
+
+fn two_container_topology(prefix: &str, b_cmd: Option<Vec<String>>) -> Topology {
+    let network = format!("{}-net", prefix);
+
+    let mut topology = Topology::new();
+    topology.networks.push(TestNetwork {
+        name: network.clone(),
+    });
+    topology.containers.push(TestContainer {
+        name: format!("{}-a", prefix),
+        image: "busybox".to_owned(),
+        network: network.clone(),
+        cmd: None,
+    });
+    topology.containers.push(TestContainer {
+        name: format!("{}-b", prefix),
+        image: "busybox".to_owned(),
+        network,
+        cmd: b_cmd,
+    });
+
+    topology
+}
+
+#[test]
+fn container_to_container_is_reachable_on_a_shared_network() {
+    let _guard = IPTABLES_LOCK.lock().unwrap();
+
+    let docker = Docker::new();
+    let topology = two_container_topology("dfw-c2c", None);
+    let logger = Logger::root(slog::Discard, o!());
+
+    let reachable = topology
+        .scoped(&docker, || {
+            let address_b = topology.container_address(&docker, "dfw-c2c-b", "dfw-c2c-net")?;
+
+            let mut dfw = DFW::default();
+            dfw.container_to_container = Some(RuleSet {
+                rules: vec![format!("-d {} -j ACCEPT", address_b)],
+            });
+
+            let mut ctx =
+                conformance_context(&docker, &dfw, &ProcessingOptions::default(), &logger)?;
+            run_conformance_pass(&mut ctx)?;
+
+            topology.assert_reachable(&docker, "dfw-c2c-a", "dfw-c2c-b", None)
+        })
+        .expect("failed to run container-to-container conformance scenario");
+
+    assert!(
+        reachable,
+        "expected container-to-container traffic to be permitted by an explicit accept rule ({})",
+        backend_under_test()
+    );
+}
+
+#[test]
+fn container_to_wider_world_is_reachable_with_an_accept_rule() {
+    let _guard = IPTABLES_LOCK.lock().unwrap();
+
+    let docker = Docker::new();
+    let mut topology = Topology::new();
+    topology.networks.push(TestNetwork {
+        name: "dfw-c2w-net".to_owned(),
+    });
+    topology.containers.push(TestContainer {
+        name: "dfw-c2w-a".to_owned(),
+        image: "busybox".to_owned(),
+        network: "dfw-c2w-net".to_owned(),
+        cmd: None,
+    });
+    let logger = Logger::root(slog::Discard, o!());
+
+    let reachable = topology
+        .scoped(&docker, || {
+            let mut dfw = DFW::default();
+            dfw.container_to_wider_world = Some(RuleSet {
+                rules: vec!["-j ACCEPT".to_owned()],
+            });
+
+            let mut ctx =
+                conformance_context(&docker, &dfw, &ProcessingOptions::default(), &logger)?;
+            run_conformance_pass(&mut ctx)?;
+
+            topology.assert_reachable(&docker, "dfw-c2w-a", "1.1.1.1", None)
+        })
+        .expect("failed to run container-to-wider-world conformance scenario");
+
+    assert!(
+        reachable,
+        "expected container-to-wider-world traffic to be permitted by an accept rule ({})",
+        backend_under_test()
+    );
+}
+
+#[test]
+fn wider_world_to_container_is_blocked_without_a_dnat_rule() {
+    let _guard = IPTABLES_LOCK.lock().unwrap();
+
+    let docker = Docker::new();
+    let topology = two_container_topology(
+        "dfw-w2c",
+        Some(vec!["nc".to_owned(), "-lp".to_owned(), "8080".to_owned()]),
+    );
+    let logger = Logger::root(slog::Discard, o!());
+
+    // `dfw-w2c-a` stands in for "the wider world", probing `dfw-w2c-b`, which has a real
+    // listener on 8080. `container_dnat` is left unconfigured and `wider_world_to_container`
+    // drops traffic to that port, so no rule DFW generated exposes it.
+    let reachable = topology
+        .scoped(&docker, || {
+            let address_b = topology.container_address(&docker, "dfw-w2c-b", "dfw-w2c-net")?;
+
+            let mut dfw: DFW<ConformanceBackend> = DFW::default();
+            dfw.wider_world_to_container = Some(RuleSet {
+                rules: vec![format!("-d {} -p tcp --dport 8080 -j DROP", address_b)],
+            });
+
+            let mut ctx =
+                conformance_context(&docker, &dfw, &ProcessingOptions::default(), &logger)?;
+            run_conformance_pass(&mut ctx)?;
+
+            topology.assert_reachable(&docker, "dfw-w2c-a", "dfw-w2c-b", Some(8080))
+        })
+        .expect("failed to run wider-world-to-container conformance scenario");
+
+    assert!(
+        !reachable,
+        "expected wider-world-to-container traffic to be blocked absent a DNAT rule ({})",
+        backend_under_test()
+    );
+}